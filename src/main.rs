@@ -1,7 +1,8 @@
+use std::collections::HashMap;
 use std::fs::{self, Metadata};
 use std::path::{Path, PathBuf};
 use std::process::Command;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 use std::io;
 
 /// Helper function to get file creation or modification time in seconds
@@ -9,63 +10,925 @@ fn get_file_timestamp(metadata: &Metadata) -> Option<SystemTime> {
     metadata.created().or_else(|_| metadata.modified()).ok()
 }
 
-/// Function to calculate the average framerate based on file timestamps
-fn calculate_framerate(image_files: &[PathBuf]) -> Option<f64> {
-    if image_files.len() < 2 {
+/// Timing knobs for the concat-demuxer frame list: how far a single frame's
+/// real capture gap is allowed to stretch or shrink, and the fixed rate to
+/// fall back to when there isn't enough timestamp data to go variable.
+#[derive(Clone, Copy, Debug)]
+struct TimingSettings {
+    min_frame_duration: Duration,
+    max_frame_duration: Duration,
+    fallback_framerate: f64,
+}
+
+impl Default for TimingSettings {
+    fn default() -> Self {
+        TimingSettings {
+            min_frame_duration: Duration::from_millis(1),
+            max_frame_duration: Duration::from_secs(10),
+            fallback_framerate: 24.0,
+        }
+    }
+}
+
+/// Computes the real capture gap before each frame (after the first) from
+/// high-resolution file timestamps, keeping sub-second precision instead of
+/// collapsing it into a single average. Each gap is clamped to
+/// `[min_frame_duration, max_frame_duration]` so a stalled capture or a
+/// clock jump can't smear into one absurd frame. A file whose timestamp
+/// can't be read gets a fallback gap rather than being dropped from the
+/// list entirely — the gaps stay positionally aligned with `image_files`
+/// (one gap per consecutive pair), which callers rely on. Returns `None`
+/// when fewer than two frames have a usable timestamp at all, so the
+/// caller can fall back to a fixed framerate for the whole batch.
+fn calculate_frame_gaps(image_files: &[PathBuf], timing: &TimingSettings) -> Option<Vec<Duration>> {
+    let timestamps: Vec<Option<SystemTime>> = image_files
+        .iter()
+        .map(|path| fs::metadata(path).ok().and_then(|m| get_file_timestamp(&m)))
+        .collect();
+
+    if timestamps.iter().filter(|t| t.is_some()).count() < 2 {
         return None;
     }
 
-    let mut time_diffs = Vec::new();
-    
-    for i in 1..image_files.len() {
-        let meta_prev = fs::metadata(&image_files[i - 1]).ok()?;
-        let meta_curr = fs::metadata(&image_files[i]).ok()?;
+    let fallback_gap = Duration::from_secs_f64(1.0 / timing.fallback_framerate)
+        .clamp(timing.min_frame_duration, timing.max_frame_duration);
+
+    let gaps = timestamps
+        .windows(2)
+        .map(|pair| match (pair[0], pair[1]) {
+            (Some(prev), Some(curr)) => curr
+                .duration_since(prev)
+                .unwrap_or(Duration::ZERO)
+                .clamp(timing.min_frame_duration, timing.max_frame_duration),
+            _ => fallback_gap,
+        })
+        .collect();
+
+    Some(gaps)
+}
+
+/// Builds a per-frame duration list, one entry per frame in `image_files`,
+/// using the real capture gaps where available and repeating the final gap
+/// for the last frame (the concat demuxer has nothing to diff it against).
+/// Falls back to `fallback_framerate` uniformly when timestamps aren't usable.
+/// The result always has exactly `image_files.len()` entries.
+fn build_frame_durations(image_files: &[PathBuf], timing: &TimingSettings) -> Vec<Duration> {
+    let durations = match calculate_frame_gaps(image_files, timing) {
+        Some(gaps) => {
+            let mut durations = gaps;
+            let last = durations.last().copied().unwrap_or(timing.min_frame_duration);
+            durations.push(last);
+            durations
+        }
+        None => {
+            let fallback = Duration::from_secs_f64(1.0 / timing.fallback_framerate);
+            vec![fallback; image_files.len()]
+        }
+    };
+
+    debug_assert_eq!(durations.len(), image_files.len());
+    durations
+}
+
+/// Writes an ffmpeg concat-demuxer list file pairing each frame with its
+/// real duration. The last `file` line is duplicated without a `duration`,
+/// since the demuxer ignores the final entry's duration otherwise.
+fn write_concat_list(image_files: &[PathBuf], durations: &[Duration]) -> io::Result<PathBuf> {
+    debug_assert_eq!(
+        image_files.len(),
+        durations.len(),
+        "every frame must have a duration or it silently drops out of the concat list"
+    );
+
+    let list_path = std::env::temp_dir().join(format!("jpg_to_webm-concat-{}.txt", std::process::id()));
+
+    let mut contents = String::new();
+    for (path, duration) in image_files.iter().zip(durations) {
+        contents.push_str(&format!("file '{}'\n", path.to_string_lossy()));
+        contents.push_str(&format!("duration {:.6}\n", duration.as_secs_f64()));
+    }
+    if let Some(last) = image_files.last() {
+        contents.push_str(&format!("file '{}'\n", last.to_string_lossy()));
+    }
+
+    fs::write(&list_path, contents)?;
+    Ok(list_path)
+}
+
+#[cfg(test)]
+mod frame_timing_tests {
+    use super::*;
+
+    #[test]
+    fn build_frame_durations_matches_frame_count_even_with_missing_timestamps() {
+        // Simulate "file c is unreadable" by pointing at paths that don't
+        // exist on disk, interleaved with ones that do (this file itself).
+        let real = PathBuf::from(file!());
+        let missing = PathBuf::from("/nonexistent/jpg_to_webm-test-missing.jpg");
+        let files = vec![real.clone(), real.clone(), missing, real];
+
+        let timing = TimingSettings::default();
+        let durations = build_frame_durations(&files, &timing);
+
+        assert_eq!(durations.len(), files.len());
+    }
+
+    #[test]
+    fn build_frame_durations_falls_back_with_fewer_than_two_timestamps() {
+        let missing = PathBuf::from("/nonexistent/jpg_to_webm-test-missing.jpg");
+        let files = vec![missing.clone(), missing];
+
+        let timing = TimingSettings::default();
+        let durations = build_frame_durations(&files, &timing);
 
-        let timestamp_prev = get_file_timestamp(&meta_prev)?.duration_since(SystemTime::UNIX_EPOCH).ok()?.as_secs_f64();
-        let timestamp_curr = get_file_timestamp(&meta_curr)?.duration_since(SystemTime::UNIX_EPOCH).ok()?.as_secs_f64();
-        
-        let time_diff = timestamp_curr - timestamp_prev;
-        time_diffs.push(time_diff);
+        let expected = Duration::from_secs_f64(1.0 / timing.fallback_framerate);
+        assert_eq!(durations, vec![expected, expected]);
     }
 
-    let avg_time_diff = time_diffs.iter().sum::<f64>() / time_diffs.len() as f64;
-    let framerate = 1.0 / avg_time_diff; // Framerate is 1 divided by the average time between frames
+    #[test]
+    fn calculate_frame_gaps_clamps_to_configured_bounds() {
+        let timing = TimingSettings {
+            min_frame_duration: Duration::from_millis(50),
+            max_frame_duration: Duration::from_millis(500),
+            fallback_framerate: 24.0,
+        };
+        // The same file twice gives a zero gap, which is raised to the minimum.
+        let real = PathBuf::from(file!());
+        let gaps = calculate_frame_gaps(&[real.clone(), real], &timing);
+        assert_eq!(gaps, Some(vec![timing.min_frame_duration]));
 
-    Some(framerate)
+        let gap = Duration::from_secs(3600).clamp(timing.min_frame_duration, timing.max_frame_duration);
+        assert_eq!(gap, timing.max_frame_duration);
+    }
+}
+
+/// Video codec to encode with. VP9 is the safe default for `.webm`; AV1
+/// trades encode speed for noticeably smaller files at the same quality.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Codec {
+    Vp9,
+    Av1,
+}
+
+impl Codec {
+    /// File extension that matches this codec's usual container.
+    fn extension(&self) -> &'static str {
+        match self {
+            Codec::Vp9 => "webm",
+            Codec::Av1 => "mkv",
+        }
+    }
+}
+
+/// Encoding backend. `Vaapi` is only used when the device it names is
+/// actually present; otherwise encoding falls back to the software codec.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Backend {
+    Software,
+    Vaapi,
+}
+
+/// Intro/outro title card bookending a video, crossfaded into and out of
+/// the footage. The template supports `{event}` and `{date_range}`.
+#[derive(Clone, Debug)]
+struct TitleCardSettings {
+    duration: Duration,
+    fade_duration: Duration,
+    title_template: String,
+}
+
+impl Default for TitleCardSettings {
+    fn default() -> Self {
+        TitleCardSettings {
+            duration: Duration::from_secs(3),
+            fade_duration: Duration::from_millis(200),
+            title_template: "Event {event} \u{2014} {date_range}".to_string(),
+        }
+    }
+}
+
+/// Encoding settings threaded through video generation. `Default` picks
+/// constant-quality VP9 (CRF 31 with `-b:v 0`).
+#[derive(Clone, Debug)]
+struct EncodeSettings {
+    codec: Codec,
+    /// CRF/quality value (lower is higher quality; meaning depends on codec).
+    crf: u32,
+    /// Optional target bitrate (e.g. "2M"). `None` means constant-quality mode.
+    target_bitrate: Option<String>,
+    /// AV1-only encode speed/efficiency tradeoff (0 slowest/smallest - 13 fastest).
+    av1_preset: u32,
+    keyframe_interval: u32,
+    /// When set, bookends the footage with a crossfaded title card.
+    title_cards: Option<TitleCardSettings>,
+    /// Preferred encoding backend; falls back to software if unavailable.
+    backend: Backend,
+    /// VAAPI render node to probe and encode through, e.g. `/dev/dri/renderD128`.
+    vaapi_device: PathBuf,
+    /// Optional `systemd-run --scope -p MemoryMax=<limit>` ceiling for the
+    /// ffmpeg process (e.g. "2G"). Ignored when `systemd-run` isn't present.
+    memory_limit: Option<String>,
+}
+
+impl Default for EncodeSettings {
+    fn default() -> Self {
+        EncodeSettings {
+            codec: Codec::Vp9,
+            crf: 31,
+            target_bitrate: None,
+            av1_preset: 7,
+            keyframe_interval: 240,
+            title_cards: None,
+            backend: Backend::Software,
+            vaapi_device: PathBuf::from("/dev/dri/renderD128"),
+            memory_limit: None,
+        }
+    }
+}
+
+impl EncodeSettings {
+    fn av1() -> Self {
+        EncodeSettings {
+            codec: Codec::Av1,
+            crf: 28,
+            ..EncodeSettings::default()
+        }
+    }
+
+    fn with_title_cards(mut self, title_cards: TitleCardSettings) -> Self {
+        self.title_cards = Some(title_cards);
+        self
+    }
+
+    fn vaapi() -> Self {
+        EncodeSettings {
+            backend: Backend::Vaapi,
+            ..EncodeSettings::default()
+        }
+    }
+
+    fn with_memory_limit(mut self, limit: impl Into<String>) -> Self {
+        self.memory_limit = Some(limit.into());
+        self
+    }
+
+    /// Resolves the configured backend down to what's actually usable,
+    /// falling back to software encoding when VAAPI was requested but the
+    /// render node isn't there.
+    fn resolve_backend(&self) -> Backend {
+        match self.backend {
+            Backend::Vaapi if self.vaapi_device.exists() => Backend::Vaapi,
+            _ => Backend::Software,
+        }
+    }
+
+    /// VAAPI codec name for the configured codec, e.g. `vp9_vaapi`.
+    fn vaapi_codec_name(&self) -> &'static str {
+        match self.codec {
+            Codec::Vp9 => "vp9_vaapi",
+            Codec::Av1 => "av1_vaapi",
+        }
+    }
+
+    /// Codec-specific ffmpeg arguments, e.g. `-c:v libvpx-vp9 -crf 31 -b:v 0`.
+    fn codec_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        let bitrate = self.target_bitrate.clone().unwrap_or_else(|| "0".to_string());
+
+        match self.codec {
+            Codec::Vp9 => {
+                args.push("-c:v".to_string());
+                args.push("libvpx-vp9".to_string());
+                args.push("-crf".to_string());
+                args.push(self.crf.to_string());
+                args.push("-b:v".to_string());
+                args.push(bitrate);
+            }
+            Codec::Av1 => {
+                args.push("-c:v".to_string());
+                args.push("libsvtav1".to_string());
+                args.push("-preset".to_string());
+                args.push(self.av1_preset.to_string());
+                args.push("-crf".to_string());
+                args.push(self.crf.to_string());
+                if let Some(bitrate) = &self.target_bitrate {
+                    args.push("-b:v".to_string());
+                    args.push(bitrate.clone());
+                }
+            }
+        }
+
+        args.push("-g".to_string());
+        args.push(self.keyframe_interval.to_string());
+
+        args
+    }
+}
+
+#[cfg(test)]
+mod encode_settings_tests {
+    use super::*;
+
+    #[test]
+    fn vp9_default_uses_constant_quality() {
+        let args = EncodeSettings::default().codec_args();
+        assert_eq!(
+            args,
+            vec!["-c:v", "libvpx-vp9", "-crf", "31", "-b:v", "0", "-g", "240"]
+        );
+    }
+
+    #[test]
+    fn av1_omits_bitrate_flag_when_unset() {
+        let args = EncodeSettings::av1().codec_args();
+        assert_eq!(
+            args,
+            vec!["-c:v", "libsvtav1", "-preset", "7", "-crf", "28", "-g", "240"]
+        );
+    }
+
+    #[test]
+    fn av1_includes_bitrate_flag_when_set() {
+        let mut settings = EncodeSettings::av1();
+        settings.target_bitrate = Some("2M".to_string());
+        let args = settings.codec_args();
+        assert_eq!(
+            args,
+            vec!["-c:v", "libsvtav1", "-preset", "7", "-crf", "28", "-b:v", "2M", "-g", "240"]
+        );
+    }
+
+    #[test]
+    fn resolve_backend_falls_back_to_software_when_device_is_missing() {
+        let settings = EncodeSettings {
+            vaapi_device: PathBuf::from("/nonexistent/renderD128"),
+            ..EncodeSettings::vaapi()
+        };
+        assert_eq!(settings.resolve_backend(), Backend::Software);
+    }
+
+    #[test]
+    fn resolve_backend_keeps_software_when_not_requested() {
+        let settings = EncodeSettings::default();
+        assert_eq!(settings.backend, Backend::Software);
+        assert_eq!(settings.resolve_backend(), Backend::Software);
+    }
+
+    #[test]
+    fn vaapi_codec_name_matches_configured_codec() {
+        assert_eq!(EncodeSettings::default().vaapi_codec_name(), "vp9_vaapi");
+        assert_eq!(EncodeSettings::av1().vaapi_codec_name(), "av1_vaapi");
+    }
+}
+
+/// Result of probing a directory's candidate frames with ffprobe: which
+/// files are usable JPEGs, which were dropped and why, and the resolution
+/// most frames agree on.
+struct ValidationReport {
+    accepted: Vec<PathBuf>,
+    rejected: Vec<(PathBuf, String)>,
+    target_resolution: Option<(u32, u32)>,
+}
+
+/// Runs ffprobe on a single file and returns `(codec_name, width, height)`.
+fn probe_image(path: &Path) -> Option<(String, u32, u32)> {
+    let output = Command::new("ffprobe")
+        .args(&[
+            "-v", "error",
+            "-select_streams", "v:0",
+            "-show_entries", "stream=codec_name,width,height",
+            "-of", "default=noprint_wrappers=1:nokey=1",
+        ])
+        .arg(path)
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut lines = stdout.lines();
+    let codec_name = lines.next()?.trim().to_string();
+    let width = lines.next()?.trim().parse().ok()?;
+    let height = lines.next()?.trim().parse().ok()?;
+
+    Some((codec_name, width, height))
+}
+
+/// Probes every candidate file with ffprobe and drops anything that isn't
+/// an actual JPEG stream (truncated files, zero-byte files, other formats
+/// wearing a `.jpg` extension), so a single bad capture can't abort or
+/// corrupt the whole encode.
+fn validate_images(image_files: &[PathBuf]) -> ValidationReport {
+    let mut probed = Vec::new();
+    let mut rejected = Vec::new();
+
+    for path in image_files {
+        match probe_image(path) {
+            Some((codec_name, width, height)) if codec_name == "mjpeg" || codec_name == "jpeg" => {
+                probed.push((path.clone(), width, height));
+            }
+            Some((codec_name, _, _)) => {
+                rejected.push((path.clone(), format!("not a JPEG stream (codec: {})", codec_name)));
+            }
+            None => {
+                rejected.push((path.clone(), "ffprobe could not read this file".to_string()));
+            }
+        }
+    }
+
+    let dims: Vec<(u32, u32)> = probed.iter().map(|(_, w, h)| (*w, *h)).collect();
+    let target_resolution = modal_resolution(&dims);
+
+    let accepted = probed.into_iter().map(|(path, _, _)| path).collect();
+
+    ValidationReport { accepted, rejected, target_resolution }
+}
+
+/// Picks the most common `(width, height)` pair in `dims`, i.e. the
+/// resolution the majority of frames were actually captured at.
+fn modal_resolution(dims: &[(u32, u32)]) -> Option<(u32, u32)> {
+    let mut counts: HashMap<(u32, u32), usize> = HashMap::new();
+    for dim in dims {
+        *counts.entry(*dim).or_insert(0) += 1;
+    }
+
+    counts.into_iter().max_by_key(|(_, count)| *count).map(|(dims, _)| dims)
+}
+
+#[cfg(test)]
+mod validation_tests {
+    use super::*;
+
+    #[test]
+    fn modal_resolution_picks_the_majority_dimensions() {
+        let dims = vec![(1920, 1080), (1920, 1080), (640, 480), (1920, 1080)];
+        assert_eq!(modal_resolution(&dims), Some((1920, 1080)));
+    }
+
+    #[test]
+    fn modal_resolution_of_empty_input_is_none() {
+        assert_eq!(modal_resolution(&[]), None);
+    }
+
+    #[test]
+    fn modal_resolution_of_single_dimension_is_itself() {
+        let dims = vec![(1280, 720)];
+        assert_eq!(modal_resolution(&dims), Some((1280, 720)));
+    }
+}
+
+/// Converts a Unix timestamp to a `YYYY-MM-DD` date string using Howard
+/// Hinnant's days-from-civil algorithm, so a title card date doesn't need
+/// to pull in a date/time dependency for something this simple.
+fn format_date(unix_seconds: i64) -> String {
+    let days = unix_seconds.div_euclid(86_400);
+    let z = days + 719_468;
+    let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let y = yoe + era * 400;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = if m <= 2 { y + 1 } else { y };
+    format!("{:04}-{:02}-{:02}", y, m, d)
+}
+
+/// Fills in a title card template with the event id and the date range
+/// spanned by its accepted frames.
+fn title_card_text(template: &str, event_id_dir: &Path, accepted: &[PathBuf]) -> String {
+    let event_name = event_id_dir.file_name().unwrap().to_string_lossy().to_string();
+
+    let mut timestamps: Vec<i64> = accepted
+        .iter()
+        .filter_map(|path| fs::metadata(path).ok().and_then(|m| get_file_timestamp(&m)))
+        .filter_map(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs() as i64)
+        .collect();
+    timestamps.sort();
+
+    let date_range = match (timestamps.first(), timestamps.last()) {
+        (Some(&start), Some(&end)) if start != end => {
+            format!("{} to {}", format_date(start), format_date(end))
+        }
+        (Some(&start), _) => format_date(start),
+        _ => "unknown date".to_string(),
+    };
+
+    template
+        .replace("{event}", &event_name)
+        .replace("{date_range}", &date_range)
+}
+
+/// Builds the extra lavfi inputs and `filter_complex` chain needed to
+/// bookend `[0:v]` (the already-assembled footage) with a title card that
+/// crossfades in and out, scaled to the footage's own resolution. Returns
+/// the extra `-i` args to append, the filter graph, and the label of the
+/// final video stream to `-map`.
+fn build_title_card_chain(
+    resolution: (u32, u32),
+    footage_duration: Duration,
+    title: &str,
+    card: &TitleCardSettings,
+    upload_to_vaapi: bool,
+) -> (Vec<String>, String, String) {
+    let (width, height) = resolution;
+    let fade = card.fade_duration.as_secs_f64();
+    let card_secs = card.duration.as_secs_f64();
+    let footage_secs = footage_duration.as_secs_f64();
+    let fontsize = height / 15;
+    let escaped_title = title.replace('\\', "\\\\").replace(':', "\\:").replace('\'', "\\'");
+
+    let inputs = vec![
+        "-f".to_string(), "lavfi".to_string(),
+        "-i".to_string(), format!("color=c=black:s={}x{}:d={}", width, height, card_secs),
+        "-f".to_string(), "lavfi".to_string(),
+        "-i".to_string(), format!("color=c=black:s={}x{}:d={}", width, height, card_secs),
+    ];
+
+    // The first crossfade starts `fade` seconds before the intro card ends;
+    // the second starts `fade` seconds before the intro+footage stream ends.
+    let intro_offset = card_secs - fade;
+    let outro_offset = card_secs + footage_secs - 2.0 * fade;
+
+    let mut filter = format!(
+        "[0:v]scale={w}:{h}:force_original_aspect_ratio=decrease,pad={w}:{h}:(ow-iw)/2:(oh-ih)/2[main];\
+         [1:v]drawtext=text='{title}':fontcolor=white:fontsize={fontsize}:x=(w-text_w)/2:y=(h-text_h)/2[intro];\
+         [2:v]drawtext=text='{title}':fontcolor=white:fontsize={fontsize}:x=(w-text_w)/2:y=(h-text_h)/2[outro];\
+         [intro][main]xfade=transition=fade:duration={fade}:offset={intro_offset}[v1];\
+         [v1][outro]xfade=transition=fade:duration={fade}:offset={outro_offset}[vout]",
+        w = width,
+        h = height,
+        title = escaped_title,
+        fontsize = fontsize,
+        fade = fade,
+        intro_offset = intro_offset,
+        outro_offset = outro_offset,
+    );
+
+    // The title card chain is built from CPU filters (drawtext, xfade); when
+    // encoding through VAAPI the result still needs to be handed to the GPU
+    // surface before the hardware encoder can see it.
+    let main_label = if upload_to_vaapi {
+        filter.push_str(";[vout]format=nv12,hwupload[vout_hw]");
+        "vout_hw"
+    } else {
+        "vout"
+    };
+
+    (inputs, filter, main_label.to_string())
+}
+
+#[cfg(test)]
+mod title_card_tests {
+    use super::*;
+
+    #[test]
+    fn format_date_matches_known_dates() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(1_700_000_000), "2023-11-14");
+        assert_eq!(format_date(951_782_400), "2000-02-29"); // leap day
+    }
+
+    #[test]
+    fn title_offsets_start_one_fade_before_each_boundary() {
+        let card = TitleCardSettings {
+            duration: Duration::from_secs(3),
+            fade_duration: Duration::from_millis(200),
+            title_template: "Event {event} \u{2014} {date_range}".to_string(),
+        };
+        let footage_duration = Duration::from_secs(10);
+        let (inputs, filter, main_label) =
+            build_title_card_chain((1280, 720), footage_duration, "Event 1", &card, false);
+
+        assert_eq!(inputs.len(), 8, "one -f lavfi -i ... pair per intro/outro card");
+        assert_eq!(main_label, "vout");
+        // intro_offset = card_secs - fade = 3.0 - 0.2 = 2.8
+        assert!(filter.contains("offset=2.8"));
+        // outro_offset = card_secs + footage_secs - 2*fade = 3.0 + 10.0 - 0.4 = 12.6
+        assert!(filter.contains("offset=12.6"));
+    }
+
+    #[test]
+    fn title_text_escapes_colons_and_quotes_for_drawtext() {
+        let card = TitleCardSettings::default();
+        let (_, filter, _) =
+            build_title_card_chain((640, 480), Duration::from_secs(1), "10:30 o'clock", &card, false);
+        assert!(filter.contains("10\\:30 o\\'clock"));
+    }
+
+    #[test]
+    fn title_card_chain_uploads_to_vaapi_when_requested() {
+        let card = TitleCardSettings::default();
+        let (_, filter, main_label) =
+            build_title_card_chain((640, 480), Duration::from_secs(5), "Event 1", &card, true);
+
+        assert_eq!(main_label, "vout_hw");
+        assert!(filter.ends_with(";[vout]format=nv12,hwupload[vout_hw]"));
+    }
+}
+
+/// Checks whether `systemd-run` is on PATH, so memory-capped batch runs can
+/// degrade gracefully on hosts without systemd (e.g. containers).
+fn systemd_run_available() -> bool {
+    Command::new("systemd-run")
+        .arg("--version")
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+/// Builds the ffmpeg invocation, wrapping it in `systemd-run --scope -p
+/// MemoryMax=<limit> --user` when a memory limit is configured and
+/// `systemd-run` is available, so a large batch of event directories can't
+/// exhaust system RAM.
+fn build_ffmpeg_command(settings: &EncodeSettings, args: Vec<String>) -> Command {
+    if let Some(limit) = &settings.memory_limit {
+        if systemd_run_available() {
+            let mut command = Command::new("systemd-run");
+            command
+                .arg("--scope")
+                .arg("--user")
+                .arg("-p")
+                .arg(format!("MemoryMax={}", limit))
+                .arg("ffmpeg")
+                .args(&args);
+            return command;
+        }
+    }
+
+    let mut command = Command::new("ffmpeg");
+    command.args(&args);
+    command
+}
+
+/// Derives `<event>-poster.webp` from a `<event>-video.<ext>` output path.
+fn poster_file_path(output_file: &Path) -> PathBuf {
+    let event_name = output_file
+        .file_stem()
+        .unwrap()
+        .to_string_lossy()
+        .trim_end_matches("-video")
+        .to_string();
+    output_file.with_file_name(format!("{}-poster.webp", event_name))
+}
+
+/// Builds the ffmpeg args that grab a single frame at `midpoint` seconds
+/// into `output_file`, scale it to `size` wide, optionally overlay a
+/// play-button asset centered on top, and write it to `poster_file`.
+fn build_poster_args(
+    output_file: &Path,
+    poster_file: &Path,
+    size: u32,
+    midpoint: f64,
+    overlay: Option<&Path>,
+) -> Vec<String> {
+    let mut args = vec![
+        "-ss".to_string(), midpoint.to_string(),
+        "-i".to_string(), output_file.to_string_lossy().to_string(),
+    ];
+
+    if let Some(overlay_path) = overlay {
+        args.push("-i".to_string());
+        args.push(overlay_path.to_string_lossy().to_string());
+        args.push("-filter_complex".to_string());
+        args.push(format!(
+            "[0:v]scale={size}:-1[base];[base][1:v]overlay=(W-w)/2:(H-h)/2",
+            size = size
+        ));
+    } else {
+        args.push("-vf".to_string());
+        args.push(format!("scale={}:-1", size));
+    }
+
+    args.push("-frames:v".to_string());
+    args.push("1".to_string());
+    args.push(poster_file.to_string_lossy().to_string());
+
+    args
+}
+
+/// Generates a `<event>-poster.webp` thumbnail for a finished video by
+/// grabbing the frame at the midpoint of its duration, optionally
+/// overlaying a play-button asset centered on top of it.
+fn generate_poster(output_file: &Path, size: u32, overlay: Option<&Path>) -> io::Result<PathBuf> {
+    let poster_file = poster_file_path(output_file);
+
+    let probe_output = Command::new("ffprobe")
+        .args(&[
+            "-v", "error",
+            "-show_entries", "format=duration",
+            "-of", "default=noprint_wrappers=1:nokey=1",
+        ])
+        .arg(output_file)
+        .output()?;
+    let duration: f64 = String::from_utf8_lossy(&probe_output.stdout)
+        .trim()
+        .parse()
+        .unwrap_or(0.0);
+    let midpoint = duration / 2.0;
+
+    let args = build_poster_args(output_file, &poster_file, size, midpoint, overlay);
+    let status = Command::new("ffmpeg").args(&args).status()?;
+
+    if status.success() {
+        println!("Created poster: {:?}", poster_file);
+    } else {
+        eprintln!("Failed to create poster for {:?}", output_file);
+    }
+
+    Ok(poster_file)
+}
+
+#[cfg(test)]
+mod poster_tests {
+    use super::*;
+
+    #[test]
+    fn poster_file_path_swaps_video_suffix_for_poster() {
+        let output = Path::new("/videos/0123-video.webm");
+        assert_eq!(poster_file_path(output), PathBuf::from("/videos/0123-poster.webp"));
+    }
+
+    #[test]
+    fn poster_file_path_works_for_mkv_output() {
+        let output = Path::new("/videos/0123-video.mkv");
+        assert_eq!(poster_file_path(output), PathBuf::from("/videos/0123-poster.webp"));
+    }
+
+    #[test]
+    fn poster_args_without_overlay_use_simple_scale() {
+        let output = Path::new("/videos/0123-video.webm");
+        let poster = Path::new("/videos/0123-poster.webp");
+        let args = build_poster_args(output, poster, 256, 1.5, None);
+
+        assert_eq!(
+            args,
+            vec![
+                "-ss", "1.5",
+                "-i", "/videos/0123-video.webm",
+                "-vf", "scale=256:-1",
+                "-frames:v", "1",
+                "/videos/0123-poster.webp",
+            ]
+        );
+    }
+
+    #[test]
+    fn poster_args_with_overlay_use_filter_complex() {
+        let output = Path::new("/videos/0123-video.webm");
+        let poster = Path::new("/videos/0123-poster.webp");
+        let overlay = Path::new("/assets/play-button.png");
+        let args = build_poster_args(output, poster, 256, 1.5, Some(overlay));
+
+        assert_eq!(
+            args,
+            vec![
+                "-ss", "1.5",
+                "-i", "/videos/0123-video.webm",
+                "-i", "/assets/play-button.png",
+                "-filter_complex", "[0:v]scale=256:-1[base];[base][1:v]overlay=(W-w)/2:(H-h)/2",
+                "-frames:v", "1",
+                "/videos/0123-poster.webp",
+            ]
+        );
+    }
 }
 
 /// Function to create a video from images in a directory, calculating the framerate
-fn create_webm_from_images(event_id_dir: &Path, output_dir: &Path) -> io::Result<()> {
+fn create_webm_from_images(
+    event_id_dir: &Path,
+    output_dir: &Path,
+    settings: &EncodeSettings,
+    timing: &TimingSettings,
+) -> io::Result<()> {
     let mut image_files: Vec<PathBuf> = fs::read_dir(event_id_dir)?
         .filter_map(|entry| entry.ok().map(|e| e.path()))
         .filter(|path| path.extension().and_then(|ext| ext.to_str()) == Some("jpg"))
         .collect();
-    
+
     image_files.sort(); // Ensure the images are in the correct order
 
-    // Calculate framerate based on timestamps
-    let framerate = calculate_framerate(&image_files).unwrap_or(24.0);  // Default to 24 fps if framerate can't be calculated
+    let report = validate_images(&image_files);
+    for (path, reason) in &report.rejected {
+        eprintln!("Skipping {:?}: {}", path, reason);
+    }
+    println!(
+        "Validated {} of {} frames in {:?}",
+        report.accepted.len(),
+        image_files.len(),
+        event_id_dir
+    );
+
+    if report.accepted.is_empty() {
+        eprintln!("No valid JPEG frames found in {:?}, skipping", event_id_dir);
+        return Ok(());
+    }
+
+    // Build the real per-frame timing and hand it to ffmpeg via the concat
+    // demuxer, so irregular capture intervals aren't smeared into one average.
+    let durations = build_frame_durations(&report.accepted, timing);
+    let concat_list = write_concat_list(&report.accepted, &durations)?;
 
-    // Prepare the input pattern and output file path
-    let images_pattern = event_id_dir.join("%d-capture.jpg");  // Input images
     let output_file = output_dir.join(format!(
-        "{}-video.webm",
-        event_id_dir.file_name().unwrap().to_string_lossy()
+        "{}-video.{}",
+        event_id_dir.file_name().unwrap().to_string_lossy(),
+        settings.codec.extension()
     ));
 
-    // Run ffmpeg to combine images into a .webm video
-    let status = Command::new("ffmpeg")
-        .args(&[
-            "-framerate", &framerate.to_string(),   // Use calculated framerate
-            "-i", &images_pattern.to_string_lossy(),  // Input pattern
-            "-c:v", "libvpx-vp9",             // Use VP9 codec for .webm
-            "-pix_fmt", "yuv420p",            // Set pixel format
-            &output_file.to_string_lossy()    // Output video file
-        ])
-        .status()?;
+    let resolved_backend = settings.resolve_backend();
+    if settings.backend == Backend::Vaapi && resolved_backend == Backend::Software {
+        eprintln!(
+            "VAAPI device {:?} not found, falling back to software encoding",
+            settings.vaapi_device
+        );
+    }
+
+    // Run ffmpeg to combine images into a video using the configured codec
+    let mut args = Vec::new();
+    if resolved_backend == Backend::Vaapi {
+        args.push("-vaapi_device".to_string());
+        args.push(settings.vaapi_device.to_string_lossy().to_string());
+    }
+    args.extend(vec![
+        "-f".to_string(), "concat".to_string(),
+        "-safe".to_string(), "0".to_string(),
+        "-i".to_string(), concat_list.to_string_lossy().to_string(),
+        "-vsync".to_string(), "vfr".to_string(),
+    ]);
+
+    if let Some(card) = &settings.title_cards {
+        // Bookend the footage with a crossfaded title card. This also
+        // absorbs the scale/pad step, since the card has to match the
+        // footage's resolution for the crossfade to work. The same CPU
+        // filter chain feeds the VAAPI encoder too, with an extra
+        // hwupload tacked on at the end.
+        let resolution = report.target_resolution.unwrap_or((1280, 720));
+        let footage_duration: Duration = durations.iter().sum();
+        let title = title_card_text(&card.title_template, event_id_dir, &report.accepted);
+        let (extra_inputs, filter_complex, main_label) = build_title_card_chain(
+            resolution,
+            footage_duration,
+            &title,
+            card,
+            resolved_backend == Backend::Vaapi,
+        );
+
+        args.extend(extra_inputs);
+        args.push("-filter_complex".to_string());
+        args.push(filter_complex);
+        args.push("-map".to_string());
+        args.push(format!("[{}]", main_label));
+    } else if resolved_backend == Backend::Vaapi {
+        // Hardware path keeps the filter chain simple: scale/pad (if
+        // needed) then hand the frame to the GPU surface.
+        let mut filter = String::new();
+        if let Some((width, height)) = report.target_resolution {
+            filter.push_str(&format!(
+                "scale={w}:{h}:force_original_aspect_ratio=decrease,pad={w}:{h}:(ow-iw)/2:(oh-ih)/2,",
+                w = width,
+                h = height
+            ));
+        }
+        filter.push_str("format=nv12,hwupload");
+        args.push("-vf".to_string());
+        args.push(filter);
+    } else if let Some((width, height)) = report.target_resolution {
+        // Frames whose resolution differs from the modal one get scaled and
+        // padded to match, instead of ffmpeg aborting on a size change.
+        args.push("-vf".to_string());
+        args.push(format!(
+            "scale={w}:{h}:force_original_aspect_ratio=decrease,pad={w}:{h}:(ow-iw)/2:(oh-ih)/2",
+            w = width,
+            h = height
+        ));
+    }
+
+    if resolved_backend == Backend::Vaapi {
+        args.push("-c:v".to_string());
+        args.push(settings.vaapi_codec_name().to_string());
+        args.push("-g".to_string());
+        args.push(settings.keyframe_interval.to_string());
+    } else {
+        args.extend(settings.codec_args());
+    }
+    if resolved_backend != Backend::Vaapi {
+        args.push("-pix_fmt".to_string());
+        args.push("yuv420p".to_string());            // Set pixel format
+    }
+    args.push(output_file.to_string_lossy().to_string()); // Output video file
+
+    let status = build_ffmpeg_command(settings, args).status()?;
+    let _ = fs::remove_file(&concat_list);
 
     if status.success() {
         println!("Created video: {:?}", output_file);
+        if let Err(e) = generate_poster(&output_file, 256, None) {
+            eprintln!("Failed to create poster for {:?}: {}", output_file, e);
+        }
     } else {
         eprintln!("Failed to create video for {:?}", event_id_dir);
     }
@@ -74,7 +937,11 @@ fn create_webm_from_images(event_id_dir: &Path, output_dir: &Path) -> io::Result
 }
 
 /// Main function to process all event directories
-fn process_event_directories(base_dir: &Path) -> io::Result<()> {
+fn process_event_directories(
+    base_dir: &Path,
+    settings: &EncodeSettings,
+    timing: &TimingSettings,
+) -> io::Result<()> {
     let videos_dir = base_dir.join("videos");
     fs::create_dir_all(&videos_dir)?;  // Ensure the "videos" directory exists
 
@@ -87,9 +954,9 @@ fn process_event_directories(base_dir: &Path) -> io::Result<()> {
             // If the directory name consists only of digits, it's an event directory
             if path.file_name()
                 .and_then(|name| name.to_str())
-                .map_or(false, |name| name.chars().all(char::is_numeric)) 
+                .map_or(false, |name| name.chars().all(char::is_numeric))
             {
-                create_webm_from_images(&path, &videos_dir)?;
+                create_webm_from_images(&path, &videos_dir, settings, timing)?;
             }
         }
     }
@@ -97,12 +964,122 @@ fn process_event_directories(base_dir: &Path) -> io::Result<()> {
     Ok(())
 }
 
+/// Minimal CLI: `jpg_to_webm [--dir PATH] [--codec vp9|av1] [--crf N] [--bitrate RATE]
+/// [--title-cards] [--vaapi] [--memory-limit LIMIT]`.
+/// Parses `std::env::args()` into the base directory and encode settings so
+/// the codec/quality knobs on `EncodeSettings` are actually reachable.
+fn parse_args() -> (PathBuf, EncodeSettings) {
+    parse_args_from(std::env::args().skip(1))
+}
+
+/// Parses CLI arguments out of any string iterator (`parse_args` feeds it
+/// `std::env::args()`; tests feed it a literal `Vec`). Each flag mutates
+/// only the field(s) it controls, so flags stay independent of each other
+/// regardless of the order they're passed in.
+fn parse_args_from<I: Iterator<Item = String>>(args: I) -> (PathBuf, EncodeSettings) {
+    let mut base_directory = PathBuf::from("/path/to/top-level-directory");
+    let mut settings = EncodeSettings::default();
+    let mut crf_set_explicitly = false;
+
+    let mut args = args;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dir" => {
+                if let Some(value) = args.next() {
+                    base_directory = PathBuf::from(value);
+                }
+            }
+            "--codec" => {
+                if let Some(value) = args.next() {
+                    settings.codec = match value.as_str() {
+                        "av1" => Codec::Av1,
+                        _ => Codec::Vp9,
+                    };
+                    // Pick that codec's sensible default CRF, unless the
+                    // user already set one explicitly with --crf.
+                    if !crf_set_explicitly {
+                        settings.crf = match settings.codec {
+                            Codec::Av1 => EncodeSettings::av1().crf,
+                            Codec::Vp9 => EncodeSettings::default().crf,
+                        };
+                    }
+                }
+            }
+            "--crf" => {
+                if let Some(value) = args.next().and_then(|v| v.parse().ok()) {
+                    settings.crf = value;
+                    crf_set_explicitly = true;
+                }
+            }
+            "--bitrate" => {
+                if let Some(value) = args.next() {
+                    settings.target_bitrate = Some(value);
+                }
+            }
+            "--title-cards" => {
+                settings = settings.with_title_cards(TitleCardSettings::default());
+            }
+            "--vaapi" => {
+                settings.backend = EncodeSettings::vaapi().backend;
+            }
+            "--memory-limit" => {
+                if let Some(value) = args.next() {
+                    settings = settings.with_memory_limit(value);
+                }
+            }
+            other => {
+                eprintln!("Ignoring unrecognized argument: {}", other);
+            }
+        }
+    }
+
+    (base_directory, settings)
+}
+
+#[cfg(test)]
+mod parse_args_tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> EncodeSettings {
+        let (_, settings) = parse_args_from(args.iter().map(|s| s.to_string()));
+        settings
+    }
+
+    #[test]
+    fn codec_flag_does_not_discard_earlier_flags() {
+        let settings = parse(&["--vaapi", "--memory-limit", "2G", "--codec", "av1"]);
+        assert_eq!(settings.backend, Backend::Vaapi);
+        assert_eq!(settings.memory_limit.as_deref(), Some("2G"));
+        assert_eq!(settings.codec, Codec::Av1);
+    }
+
+    #[test]
+    fn codec_flag_does_not_discard_later_flags_either() {
+        let settings = parse(&["--codec", "av1", "--vaapi", "--memory-limit", "2G"]);
+        assert_eq!(settings.backend, Backend::Vaapi);
+        assert_eq!(settings.memory_limit.as_deref(), Some("2G"));
+        assert_eq!(settings.codec, Codec::Av1);
+    }
+
+    #[test]
+    fn explicit_crf_survives_a_later_codec_flag() {
+        let settings = parse(&["--crf", "40", "--codec", "av1"]);
+        assert_eq!(settings.codec, Codec::Av1);
+        assert_eq!(settings.crf, 40);
+    }
+
+    #[test]
+    fn codec_flag_picks_its_own_default_crf_when_none_was_set() {
+        let settings = parse(&["--codec", "av1"]);
+        assert_eq!(settings.crf, EncodeSettings::av1().crf);
+    }
+}
+
 fn main() -> io::Result<()> {
-    // Set the base directory for your project
-    let base_directory = Path::new("/path/to/top-level-directory");
+    let (base_directory, settings) = parse_args();
 
-    // Process all event directories
-    process_event_directories(base_directory)?;
+    // Process all event directories with the encode settings selected on the CLI
+    process_event_directories(&base_directory, &settings, &TimingSettings::default())?;
 
     Ok(())
 }